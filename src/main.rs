@@ -1,13 +1,111 @@
 use clap::{Arg, ArgMatches, Command};
-use mdbook::book::{Book, Chapter};
+use mdbook::book::{Book, BookItem, Chapter};
 use mdbook::errors::Error;
 use mdbook::preprocess::{CmdPreprocessor, Preprocessor, PreprocessorContext};
-use pulldown_cmark::{CowStr, Event, Parser, Tag};
+use pulldown_cmark::{CowStr, Event, Options, Parser, Tag};
+use semver::{Version, VersionReq};
+use std::collections::HashSet;
 use std::io;
 use std::process;
 
 use tailwind_css::TailwindBuilder;
 
+/// How a resolved Tailwind utility should be emitted onto its target element.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EmitMode {
+    /// Resolve classes to CSS and write them as an inline `style="..."` attribute.
+    #[default]
+    Inline,
+    /// Leave the Tailwind class names on the element (e.g. `class="..."`).
+    Class,
+}
+
+/// Parsed `[preprocessor.tailwindcss]` settings from `book.toml`.
+pub struct Config {
+    /// Token that introduces a class annotation, e.g. `{:.` for `{:.class-name}`.
+    pub annotation_prefix: String,
+    /// Whether to inline resolved CSS or keep Tailwind class names.
+    pub emit: EmitMode,
+    /// If true, a processing error aborts the build instead of just logging it.
+    pub fail_on_error: bool,
+    /// Renderers this preprocessor should run for, e.g. `["html", "epub"]`.
+    pub renderers: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            annotation_prefix: "{:.".to_string(),
+            emit: EmitMode::default(),
+            fail_on_error: false,
+            renderers: default_renderers(),
+        }
+    }
+}
+
+/// Renderers supported when `[preprocessor.tailwindcss]` doesn't declare a `renderer` list.
+fn default_renderers() -> Vec<String> {
+    vec!["html".to_string()]
+}
+
+/// Read the `renderer = [...]` list out of a `[preprocessor.tailwindcss]` table, falling back
+/// to [`default_renderers`] when it's absent or empty.
+fn parse_renderers(table: &toml::value::Table) -> Vec<String> {
+    match table.get("renderer").and_then(|v| v.as_array()) {
+        Some(values) => {
+            let renderers: Vec<String> = values
+                .iter()
+                .filter_map(|v| v.as_str().map(String::from))
+                .collect();
+            if renderers.is_empty() {
+                default_renderers()
+            } else {
+                renderers
+            }
+        }
+        None => default_renderers(),
+    }
+}
+
+impl Config {
+    /// Read `[preprocessor.tailwindcss]` out of the preprocessor context, falling back to
+    /// defaults for anything missing or malformed.
+    fn from_context(ctx: &PreprocessorContext, name: &str) -> Config {
+        let mut config = Config::default();
+
+        let table = match ctx.config.get_preprocessor(name) {
+            Some(table) => table,
+            None => return config,
+        };
+
+        if let Some(prefix) = table.get("annotation_prefix").and_then(|v| v.as_str()) {
+            config.annotation_prefix = prefix.to_string();
+        }
+
+        if let Some(emit) = table.get("emit").and_then(|v| v.as_str()) {
+            config.emit = match emit {
+                "class" => EmitMode::Class,
+                "inline" => EmitMode::Inline,
+                other => {
+                    eprintln!(
+                        "tailwindcss: unknown `emit` value {:?}, expected \"inline\" or \"class\"; using \"inline\"",
+                        other
+                    );
+                    EmitMode::Inline
+                }
+            };
+        }
+
+        if let Some(fail_on_error) = table.get("fail_on_error").and_then(|v| v.as_bool()) {
+            config.fail_on_error = fail_on_error;
+        }
+
+        config.renderers = parse_renderers(table);
+
+        config
+    }
+}
+
 #[derive(Default)]
 pub struct Tailwindcss;
 
@@ -21,18 +119,81 @@ impl Preprocessor for Tailwindcss {
     fn name(&self) -> &str {
         "tailwindcss"
     }
-    fn run(&self, _ctx: &PreprocessorContext, mut book: Book) -> Result<Book, Error> {
+    fn run(&self, ctx: &PreprocessorContext, mut book: Book) -> Result<Book, Error> {
+        let config = Config::from_context(ctx, self.name());
+
+        // Pass 1: rewrite each chapter's markdown, collecting (in `EmitMode::Class` only) every
+        // distinct class name used anywhere in the book along the way.
+        let mut used_classes: HashSet<String> = HashSet::new();
+        let mut first_error = None;
         book.for_each_mut(|book| {
-            if let mdbook::BookItem::Chapter(chapter) = book {
-                if let Err(e) = process_tailwindcss(chapter) {
-                    eprintln!("tailwindcss error: {:?}", e);
+            if let BookItem::Chapter(chapter) = book {
+                match process_tailwindcss(chapter, &config) {
+                    Ok(classes) => used_classes.extend(classes),
+                    Err(e) => {
+                        if config.fail_on_error {
+                            if first_error.is_none() {
+                                first_error = Some(e);
+                            }
+                        } else {
+                            eprintln!("tailwindcss error: {:?}", e);
+                        }
+                    }
                 }
             }
         });
+
+        if let Some(e) = first_error {
+            return Err(e);
+        }
+
+        // Pass 2: compile every class used across the whole book into one stylesheet and
+        // inject it into the first chapter, since the HTML renderer's `<head>` is out of reach.
+        if config.emit == EmitMode::Class && !used_classes.is_empty() {
+            let mut classes: Vec<&str> = used_classes.iter().map(String::as_str).collect();
+            classes.sort_unstable();
+
+            let mut tailwind = TailwindBuilder::default();
+            for class in classes {
+                let _ = tailwind.trace(class, false);
+            }
+
+            match tailwind.bundle() {
+                Ok(css) => {
+                    let style_block = format!("<style>\n{}\n</style>\n\n", css);
+
+                    let mut injected = false;
+                    book.for_each_mut(|book| {
+                        if injected {
+                            return;
+                        }
+                        if let BookItem::Chapter(chapter) = book {
+                            if chapter.path.is_some() {
+                                chapter.content = format!("{}{}", style_block, chapter.content);
+                                injected = true;
+                            }
+                        }
+                    });
+                }
+                Err(e) => {
+                    if config.fail_on_error {
+                        return Err(e.into());
+                    }
+                    eprintln!("tailwindcss error: {:?}", e);
+                }
+            }
+        }
+
         Ok(book)
     }
     fn supports_renderer(&self, renderer: &str) -> bool {
-        renderer == "html"
+        // `mdbook-tailwindcss supports <renderer>` is invoked without a PreprocessorContext, so
+        // the `[preprocessor.tailwindcss]` table has to be read straight off disk here.
+        let renderers = mdbook::Config::from_disk("book.toml")
+            .ok()
+            .and_then(|config| config.get_preprocessor(self.name()).map(parse_renderers))
+            .unwrap_or_else(default_renderers);
+        renderers.iter().any(|r| r == renderer)
     }
 }
 
@@ -43,17 +204,289 @@ struct ClassAnnotation {
     pub paragraph_end: Option<usize>,
 }
 
+/// A Kramdown-style inline attribute list: `.class`, `#id`, and `key="value"` tokens.
+struct Ial {
+    pub classes: Vec<String>,
+    pub id: Option<String>,
+    pub attrs: Vec<(String, String)>,
+}
+
+/// Parse the body of an IAL (the part between `{:` and `}`, e.g. `.foo .bar #id key="val"`).
+fn parse_ial(inner: &str) -> Ial {
+    let mut ial = Ial {
+        classes: vec![],
+        id: None,
+        attrs: vec![],
+    };
+    for token in inner.split_whitespace() {
+        if let Some(class) = token.strip_prefix('.') {
+            ial.classes.push(class.to_string());
+        } else if let Some(id) = token.strip_prefix('#') {
+            ial.id = Some(id.to_string());
+        } else if let Some((key, value)) = token.split_once('=') {
+            ial.attrs
+                .push((key.to_string(), value.trim_matches('"').to_string()));
+        } else if !token.is_empty() {
+            ial.classes.push(token.to_string());
+        }
+    }
+    ial
+}
+
+/// If `text` ends with a trailing IAL (`... {: .foo .bar #id key="val"}`), split it into the
+/// text that precedes the token and the token's inner body.
+fn strip_trailing_ial(text: &str) -> Option<(&str, &str)> {
+    let trimmed = text.trim_end();
+    if !trimmed.ends_with('}') {
+        return None;
+    }
+    let idx = trimmed.rfind("{:")?;
+    let inner = trimmed[idx + 2..trimmed.len() - 1].trim();
+    Some((text[..idx].trim_end(), inner))
+}
+
+/// If `text` contains an inline `word{:.class}` span, split it into the text before the word,
+/// the word itself, the class token, and the text remaining after the span.
+fn split_inline_ial(text: &str) -> Option<(String, String, String, String)> {
+    let open = text.find("{:.")?;
+    let close = text[open..].find('}')? + open;
+    let word_start = text[..open].rfind(char::is_whitespace).map_or(0, |p| p + 1);
+    if word_start == open {
+        return None;
+    }
+    Some((
+        text[..word_start].to_string(),
+        text[word_start..open].to_string(),
+        text[open + 3..close].trim().to_string(),
+        text[close + 1..].to_string(),
+    ))
+}
+
+/// Resolve a list of Tailwind class tokens into `(class_attr, style_attr)` per `config.emit`:
+/// `Inline` compiles each token to CSS (falling back to a literal class for unknown tokens);
+/// `Class` keeps every token as a literal class and records it in `used_classes` for bundling.
+fn resolve_classes(
+    tailwind: &mut TailwindBuilder,
+    config: &Config,
+    used_classes: &mut HashSet<String>,
+    tokens: &[String],
+) -> (String, String) {
+    let mut classes = vec![];
+    let mut styles = vec![];
+    match config.emit {
+        EmitMode::Inline => {
+            for token in tokens {
+                match tailwind.inline(token) {
+                    Ok(r) => styles.push(r.1),
+                    Err(_) => classes.push(token.clone()),
+                }
+            }
+        }
+        EmitMode::Class => {
+            for token in tokens {
+                used_classes.insert(token.clone());
+                classes.push(token.clone());
+            }
+        }
+    }
+    (classes.join(" "), styles.join(""))
+}
+
+/// Render a `<div ...>` (or `<span ...>`) opening tag carrying the resolved class/style plus
+/// any id and extra attributes from an IAL.
+fn wrap_open_tag(tag: &str, class_attr: &str, style_attr: &str, ial: &Ial) -> String {
+    let mut open = format!("<{}", tag);
+    if !class_attr.is_empty() {
+        open.push_str(&format!(" class=\"{}\"", class_attr));
+    }
+    if !style_attr.is_empty() {
+        open.push_str(&format!(" style=\"{}\"", style_attr));
+    }
+    if let Some(id) = &ial.id {
+        open.push_str(&format!(" id=\"{}\"", id));
+    }
+    for (key, value) in &ial.attrs {
+        open.push_str(&format!(" {}=\"{}\"", key, value));
+    }
+    open.push('>');
+    open
+}
+
+/// A block (heading, list item, blockquote, or table) whose trailing IAL should be stripped and
+/// replaced with a wrapping `<div>` carrying the resolved classes/id/attrs.
+///
+/// Code blocks are deliberately not supported here: a fenced code block's `End` is never
+/// preceded by a `Text` event that belongs to an IAL (the block's own literal content isn't
+/// annotation syntax, and a `{: ...}` placed after the closing fence starts a new, sibling
+/// `Paragraph` instead).
+struct BlockIal {
+    pub start: usize,
+    pub end: usize,
+    pub text_index: usize,
+    pub remaining_text: String,
+    pub ial: Ial,
+}
+
+/// Kramdown lets an IAL trail any of these block kinds, not just a leading paragraph. Tables
+/// only produce `Tag::Table`/`TableHead`/`TableRow`/`TableCell` events when the parser is run
+/// with `Options::ENABLE_TABLES`, which `process_tailwindcss` enables.
+fn block_end_matches(tag: &Tag) -> bool {
+    matches!(tag, Tag::Heading(..) | Tag::Item | Tag::BlockQuote | Tag::Table(..))
+}
+
+/// Find the `Text` event (if any) that a trailing IAL could live in for the block whose `End`
+/// is at `end_index`. Usually that's the event right before `End` (e.g. a heading's own text),
+/// but some blocks nest their content one or more levels deep: "loose" list items and
+/// blockquotes wrap theirs in a `Paragraph`, and a table's last cell is reached through
+/// `End(TableRow)` (or `End(TableHead)` for a header-only table) and then `End(TableCell)`.
+/// Unwind through that chain of wrapper `End`s until a `Text` event turns up.
+fn trailing_text_index(events: &[Event], end_index: usize) -> Option<usize> {
+    let mut idx = end_index;
+    loop {
+        if idx == 0 {
+            return None;
+        }
+        idx -= 1;
+        match &events[idx] {
+            Event::Text(_) => return Some(idx),
+            Event::End(Tag::Paragraph)
+            | Event::End(Tag::TableHead)
+            | Event::End(Tag::TableRow)
+            | Event::End(Tag::TableCell) => continue,
+            _ => return None,
+        }
+    }
+}
+
+/// Generalizes the leading `{:.class}` paragraph annotation to a trailing IAL on any of the
+/// block kinds in [`block_end_matches`], and to an inline `word{:.class}` span wrapped in a
+/// `<span>`. Operates on the already-rewritten event stream from the leading-paragraph pass.
+fn apply_kramdown_ial<'a>(
+    events: Vec<Event<'a>>,
+    config: &Config,
+    tailwind: &mut TailwindBuilder,
+    used_classes: &mut HashSet<String>,
+) -> Vec<Event<'a>> {
+    // Pass 1: trailing block IAL. Track the start of every open block kind we support so we
+    // know the span to wrap once we see its matching End with a trailing IAL before it.
+    let mut open_starts: Vec<usize> = vec![];
+    let mut block_ials: Vec<BlockIal> = vec![];
+    for (i, event) in events.iter().enumerate() {
+        match event {
+            Event::Start(tag) if block_end_matches(tag) => open_starts.push(i),
+            Event::End(tag) if block_end_matches(tag) => {
+                if let Some(start) = open_starts.pop() {
+                    if let Some(text_index) = trailing_text_index(&events, i) {
+                        if let Event::Text(text) = &events[text_index] {
+                            if let Some((before, inner)) = strip_trailing_ial(text) {
+                                block_ials.push(BlockIal {
+                                    start,
+                                    end: i,
+                                    text_index,
+                                    remaining_text: before.to_string(),
+                                    ial: parse_ial(inner),
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut wrapped = vec![];
+    let mut cursor = 0;
+    for block in &block_ials {
+        wrapped.extend(events[cursor..block.start].iter().cloned());
+
+        let (class_attr, style_attr) =
+            resolve_classes(tailwind, config, used_classes, &block.ial.classes);
+        wrapped.push(Event::Html(CowStr::from(wrap_open_tag(
+            "div",
+            &class_attr,
+            &style_attr,
+            &block.ial,
+        ))));
+
+        wrapped.extend(events[block.start..block.text_index].iter().cloned());
+        if !block.remaining_text.is_empty() {
+            wrapped.push(Event::Text(CowStr::from(block.remaining_text.clone())));
+        }
+        wrapped.extend(events[block.text_index + 1..=block.end].iter().cloned());
+
+        wrapped.push(Event::Html(CowStr::from("</div>")));
+        cursor = block.end + 1;
+    }
+    wrapped.extend(events[cursor..].iter().cloned());
+
+    // Pass 2: inline `word{:.class}` spans, wrapped in a <span>. A single text node can contain
+    // more than one span (e.g. "foo{:.a} and bar{:.b}"), so keep re-scanning the remainder
+    // until no more spans are found.
+    let mut out = vec![];
+    for event in wrapped {
+        match event {
+            Event::Text(text) if text.contains("{:.") => {
+                let mut remaining = text.to_string();
+                loop {
+                    match split_inline_ial(&remaining) {
+                        Some((before, word, class, after)) => {
+                            if !before.is_empty() {
+                                out.push(Event::Text(CowStr::from(before)));
+                            }
+                            let (class_attr, style_attr) =
+                                resolve_classes(tailwind, config, used_classes, &[class]);
+                            let ial = Ial {
+                                classes: vec![],
+                                id: None,
+                                attrs: vec![],
+                            };
+                            out.push(Event::Html(CowStr::from(wrap_open_tag(
+                                "span",
+                                &class_attr,
+                                &style_attr,
+                                &ial,
+                            ))));
+                            out.push(Event::Text(CowStr::from(word)));
+                            out.push(Event::Html(CowStr::from("</span>")));
+                            remaining = after;
+                        }
+                        None => {
+                            if !remaining.is_empty() {
+                                out.push(Event::Text(CowStr::from(remaining)));
+                            }
+                            break;
+                        }
+                    }
+                }
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
 /// This is where the markdown transformation actually happens.
-/// Take paragraphs beginning with `{:.class-name}` and give them special rendering.
-/// Mutation: the payload here is that it edits chapter.content.
-fn process_tailwindcss(chapter: &mut Chapter) -> Result<(), Error> {
-    // 1. Parse the inbound markdown into an Event vector.
-    let incoming_events: Vec<Event> = Parser::new(&chapter.content).collect();
+/// Take paragraphs beginning with the configured annotation prefix (default `{:.class-name}`)
+/// and give them special rendering, plus the generalized Kramdown forms in
+/// [`apply_kramdown_ial`].
+///
+/// Mutation: the payload here is that it edits chapter.content. When
+/// `config.emit == EmitMode::Class`, returns every distinct class name used in this chapter so
+/// `run` can bundle them into one stylesheet; with `EmitMode::Inline` the returned set is empty.
+fn process_tailwindcss(chapter: &mut Chapter, config: &Config) -> Result<HashSet<String>, Error> {
+    // 1. Parse the inbound markdown into an Event vector. ENABLE_TABLES is needed so pipe
+    // tables produce real Tag::Table events for the trailing-IAL pass in apply_kramdown_ial.
+    let incoming_events: Vec<Event> =
+        Parser::new_ext(&chapter.content, Options::ENABLE_TABLES).collect();
 
     let mut tailwind = TailwindBuilder::default();
+    let prefix = config.annotation_prefix.as_str();
+    let prefix_len = prefix.len();
+    let mut used_classes: HashSet<String> = HashSet::new();
 
-    // 2. Find paragraphs beginning with the class annotator `{:.class-name}` and record their information in
-    // a vector of ClassAnnotation structs.
+    // 2. Collect pass: find paragraphs beginning with the class annotator (default
+    // `{:.class-name}`) and record their information in a vector of ClassAnnotation structs.
     let mut class_annotations: Vec<ClassAnnotation> = vec![];
     for i in 0..incoming_events.len() {
         let event = &incoming_events[i];
@@ -61,23 +494,15 @@ fn process_tailwindcss(chapter: &mut Chapter) -> Result<(), Error> {
             Event::Text(CowStr::Borrowed(text)) => {
                 if i > 0 {
                     if let Event::Start(Tag::Paragraph) = incoming_events[i - 1] {
-                        let v: Vec<_> = text.split("").collect();
-                        let len_v = v.len();
-                        if v[..4].join("") == "{:." && v[(len_v - 2)..].join("") == "}" {
-                            let class = v[4..(len_v - 2)].join("").replace('.', "");
-
-                            let mut c = vec![];
-                            let mut s = vec![];
-
-                            for kls in class.split(' ') {
-                                match tailwind.inline(kls) {
-                                    Ok(r) => s.push(r.1),
-                                    Err(_) => c.push(kls),
-                                }
-                            }
+                        if text.starts_with(prefix) && text.ends_with('}') {
+                            let class = text[prefix_len..(text.len() - 1)].replace('.', "");
+                            let tokens: Vec<String> =
+                                class.split(' ').map(String::from).collect();
+                            let (class_attr, style_attr) =
+                                resolve_classes(&mut tailwind, config, &mut used_classes, &tokens);
                             class_annotations.push(ClassAnnotation {
-                                class: c.join(" "),
-                                style: s.join(""),
+                                class: class_attr,
+                                style: style_attr,
                                 paragraph_start: i - 1,
                                 paragraph_end: None,
                             })
@@ -97,22 +522,24 @@ fn process_tailwindcss(chapter: &mut Chapter) -> Result<(), Error> {
         }
     }
 
-    // 3. Construct a new_events vector with <div class="class-name">\n \n</div> around the annotated paragraphs
-    // (and with the class annotation removed).
+    // 3. Rewrite pass: construct a new_events vector with <div class="class-name">\n \n</div>
+    // around the annotated paragraphs (and with the class annotation removed).
     let mut slices = vec![];
     let mut last_end = 0;
     let div_starts: Vec<Event> = class_annotations
         .iter()
-        //.map(|ca| Event::Html(CowStr::from(format!("<div class=\"{}\">", ca.class))))
         .map(|ca| {
-            Event::Html(CowStr::from(format!(
-                "<div class\"{}\" style=\"{}\">",
-                ca.class, ca.style
-            )))
+            if ca.style.is_empty() {
+                Event::Html(CowStr::from(format!("<div class=\"{}\">", ca.class)))
+            } else {
+                Event::Html(CowStr::from(format!(
+                    "<div class=\"{}\" style=\"{}\">",
+                    ca.class, ca.style
+                )))
+            }
         })
         .collect();
     let div_end: Vec<Event> = vec![Event::Html(CowStr::from("</div>"))];
-    //let div_end: Vec<Event> = vec![Event::Html(CowStr::from("end"))];
     for (i, ca) in class_annotations.iter().enumerate() {
         // Add unclassed events.
         slices.push(&incoming_events[last_end..ca.paragraph_start]);
@@ -136,11 +563,14 @@ fn process_tailwindcss(chapter: &mut Chapter) -> Result<(), Error> {
     slices.push(&incoming_events[last_end..]);
     let new_events = slices.concat();
 
-    // 4. Update chapter.content using markdown generated from the new event vector.
+    // 4. Generalize to trailing block IAL and inline `word{:.class}` spans.
+    let new_events = apply_kramdown_ial(new_events, config, &mut tailwind, &mut used_classes);
+
+    // 5. Update chapter.content using markdown generated from the new event vector.
     let mut buf = String::with_capacity(chapter.content.len() + 128);
     pulldown_cmark_to_cmark::cmark(new_events.into_iter(), &mut buf).expect("can re-render cmark");
     chapter.content = buf;
-    Ok(())
+    Ok(used_classes)
 }
 
 /// Housekeeping:
@@ -149,9 +579,9 @@ fn process_tailwindcss(chapter: &mut Chapter) -> Result<(), Error> {
 fn handle_preprocessing(pre: &dyn Preprocessor) -> Result<(), Error> {
     let (ctx, book) = CmdPreprocessor::parse_input(io::stdin())?;
 
-    if ctx.mdbook_version != mdbook::MDBOOK_VERSION {
-        // We should probably use the `semver` crate to check compatibility
-        // here...
+    let book_version = Version::parse(&ctx.mdbook_version)?;
+    let version_req = VersionReq::parse(&format!("~{}", mdbook::MDBOOK_VERSION))?;
+    if !version_req.matches(&book_version) {
         eprintln!(
             "Warning: The {} plugin was built against version {} of mdbook, \
              but we're being called from version {}",
@@ -167,7 +597,8 @@ fn handle_preprocessing(pre: &dyn Preprocessor) -> Result<(), Error> {
     Ok(())
 }
 
-/// Check to see if we support the processor (mdbook-tailwindcss only supports html right now)
+/// Check whether `renderer` is in the book's configured `[preprocessor.tailwindcss]` renderer
+/// list (`["html"]` by default).
 fn handle_supports(pre: &dyn Preprocessor, sub_args: &ArgMatches) -> ! {
     let renderer = sub_args.value_of("renderer").expect("Required argument");
     let supported = pre.supports_renderer(&renderer);
@@ -202,3 +633,215 @@ fn main() {
         process::exit(1);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn preprocessor_context(book_toml: &str) -> PreprocessorContext {
+        let config = mdbook::Config::from_str(book_toml).unwrap();
+        let ctx_json = serde_json::json!({
+            "root": ".",
+            "config": config,
+            "renderer": "html",
+            "mdbook_version": mdbook::MDBOOK_VERSION,
+        });
+        serde_json::from_value(ctx_json).unwrap()
+    }
+
+    #[test]
+    fn config_from_context_reads_every_setting() {
+        let ctx = preprocessor_context(
+            r#"
+            [book]
+            title = "Test"
+
+            [preprocessor.tailwindcss]
+            annotation_prefix = "{{."
+            emit = "class"
+            fail_on_error = true
+            renderer = ["html", "epub"]
+            "#,
+        );
+
+        let config = Config::from_context(&ctx, "tailwindcss");
+
+        assert_eq!(config.annotation_prefix, "{{.");
+        assert_eq!(config.emit, EmitMode::Class);
+        assert!(config.fail_on_error);
+        assert_eq!(
+            config.renderers,
+            vec!["html".to_string(), "epub".to_string()]
+        );
+    }
+
+    #[test]
+    fn config_from_context_falls_back_to_defaults_when_section_is_absent() {
+        let ctx = preprocessor_context("[book]\ntitle = \"Test\"\n");
+
+        let config = Config::from_context(&ctx, "tailwindcss");
+        let default = Config::default();
+
+        assert_eq!(config.annotation_prefix, default.annotation_prefix);
+        assert_eq!(config.emit, default.emit);
+        assert_eq!(config.fail_on_error, default.fail_on_error);
+        assert_eq!(config.renderers, default.renderers);
+    }
+
+    #[test]
+    fn config_from_context_falls_back_to_inline_on_unknown_emit_value() {
+        let ctx = preprocessor_context(
+            r#"
+            [book]
+            title = "Test"
+
+            [preprocessor.tailwindcss]
+            emit = "nonsense"
+            "#,
+        );
+
+        let config = Config::from_context(&ctx, "tailwindcss");
+
+        assert_eq!(config.emit, EmitMode::Inline);
+    }
+
+    #[test]
+    fn parse_renderers_reads_renderer_array() {
+        let table =
+            toml::from_str::<toml::value::Table>(r#"renderer = ["html", "epub"]"#).unwrap();
+        assert_eq!(
+            parse_renderers(&table),
+            vec!["html".to_string(), "epub".to_string()]
+        );
+    }
+
+    #[test]
+    fn parse_renderers_falls_back_to_default_when_absent_or_empty() {
+        let empty = toml::value::Table::new();
+        assert_eq!(parse_renderers(&empty), default_renderers());
+
+        let empty_array = toml::from_str::<toml::value::Table>("renderer = []").unwrap();
+        assert_eq!(parse_renderers(&empty_array), default_renderers());
+    }
+
+    #[test]
+    fn parse_ial_splits_classes_id_and_attrs() {
+        let ial = parse_ial(".foo .bar #baz key=\"val\"");
+        assert_eq!(ial.classes, vec!["foo".to_string(), "bar".to_string()]);
+        assert_eq!(ial.id, Some("baz".to_string()));
+        assert_eq!(ial.attrs, vec![("key".to_string(), "val".to_string())]);
+    }
+
+    #[test]
+    fn strip_trailing_ial_finds_token_at_end_of_text() {
+        let (before, inner) = strip_trailing_ial("Some heading {: .red}").unwrap();
+        assert_eq!(before, "Some heading");
+        assert_eq!(inner, ".red");
+    }
+
+    #[test]
+    fn strip_trailing_ial_ignores_text_without_a_token() {
+        assert!(strip_trailing_ial("Some heading").is_none());
+    }
+
+    #[test]
+    fn split_inline_ial_extracts_one_span() {
+        let (before, word, class, after) = split_inline_ial("see foo{:.a} now").unwrap();
+        assert_eq!(before, "see ");
+        assert_eq!(word, "foo");
+        assert_eq!(class, "a");
+        assert_eq!(after, " now");
+    }
+
+    #[test]
+    fn split_inline_ial_requires_a_preceding_word() {
+        assert!(split_inline_ial("{:.a} now").is_none());
+    }
+
+    /// Guards the assumption `trailing_text_index` relies on: a tight list item's `End` is
+    /// preceded directly by its `Text`.
+    #[test]
+    fn tight_list_item_end_is_preceded_by_text() {
+        let events: Vec<Event> = Parser::new("- one\n- two\n").collect();
+        let item_end = events
+            .iter()
+            .position(|e| matches!(e, Event::End(Tag::Item)))
+            .unwrap();
+        assert!(matches!(events[item_end - 1], Event::Text(_)));
+    }
+
+    /// Guards the other branch: a "loose" list item (blank line between items) and a
+    /// blockquote both wrap their content in a `Paragraph`, so their `End` is preceded by
+    /// `End(Paragraph)`, not `Text` directly.
+    #[test]
+    fn loose_list_item_and_blockquote_end_are_preceded_by_paragraph_end() {
+        let loose_list: Vec<Event> = Parser::new("- one\n\n- two\n").collect();
+        let item_end = loose_list
+            .iter()
+            .position(|e| matches!(e, Event::End(Tag::Item)))
+            .unwrap();
+        assert!(matches!(loose_list[item_end - 1], Event::End(Tag::Paragraph)));
+        assert!(matches!(loose_list[item_end - 2], Event::Text(_)));
+
+        let blockquote: Vec<Event> = Parser::new("> quoted text {: .foo}\n").collect();
+        let quote_end = blockquote
+            .iter()
+            .position(|e| matches!(e, Event::End(Tag::BlockQuote)))
+            .unwrap();
+        assert!(matches!(blockquote[quote_end - 1], Event::End(Tag::Paragraph)));
+        assert!(matches!(blockquote[quote_end - 2], Event::Text(_)));
+    }
+
+    #[test]
+    fn apply_kramdown_ial_wraps_heading_with_trailing_ial() {
+        let config = Config::default();
+        let mut tailwind = TailwindBuilder::default();
+        let mut used_classes = HashSet::new();
+        let events: Vec<Event> = Parser::new("# Title {: .big}\n").collect();
+
+        let out = apply_kramdown_ial(events, &config, &mut tailwind, &mut used_classes);
+
+        assert!(out.iter().any(
+            |e| matches!(e, Event::Html(html) if html.starts_with("<div") && html.contains("big"))
+        ));
+        assert!(out
+            .iter()
+            .any(|e| matches!(e, Event::Text(text) if &**text == "Title")));
+    }
+
+    #[test]
+    fn apply_kramdown_ial_wraps_table_with_trailing_ial() {
+        let config = Config::default();
+        let mut tailwind = TailwindBuilder::default();
+        let mut used_classes = HashSet::new();
+        let markdown = "| a | b |\n| - | - |\n| 1 | 2 {: .striped} |\n";
+        let events: Vec<Event> = Parser::new_ext(markdown, Options::ENABLE_TABLES).collect();
+
+        let out = apply_kramdown_ial(events, &config, &mut tailwind, &mut used_classes);
+
+        assert!(out.iter().any(
+            |e| matches!(e, Event::Html(html) if html.starts_with("<div") && html.contains("striped"))
+        ));
+        assert!(out.iter().any(|e| matches!(e, Event::Start(Tag::Table(_)))));
+    }
+
+    #[test]
+    fn apply_kramdown_ial_wraps_every_inline_span_in_a_text_node() {
+        let config = Config::default();
+        let mut tailwind = TailwindBuilder::default();
+        let mut used_classes = HashSet::new();
+        let events: Vec<Event> = Parser::new("foo{:.a} and bar{:.b}\n").collect();
+
+        let out = apply_kramdown_ial(events, &config, &mut tailwind, &mut used_classes);
+
+        let span_opens = out
+            .iter()
+            .filter(|e| matches!(e, Event::Html(html) if html.starts_with("<span")))
+            .count();
+        assert_eq!(span_opens, 2);
+        assert!(out
+            .iter()
+            .any(|e| matches!(e, Event::Text(text) if &**text == "bar")));
+    }
+}